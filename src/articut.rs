@@ -1,12 +1,25 @@
+use futures::stream::{self, StreamExt};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
 use std::str::FromStr;
+use std::time::{Duration, Instant};
 use thiserror::Error;
 
 pub struct Articut<'a, 'b> {
     username: &'a str,
     api_key: &'b str,
-    client: Option<reqwest::Client>,
+    client: reqwest::Client,
+    rate_limit: Option<RateLimit>,
+    request_log: tokio::sync::Mutex<VecDeque<Instant>>,
+    quota_precheck: bool,
+    last_quota: tokio::sync::Mutex<Option<i32>>,
+}
+
+/// Sliding-window throttling config installed via [`Articut::with_rate_limit`].
+struct RateLimit {
+    max_per_minute: usize,
+    max_retries: u32,
 }
 
 #[derive(Error, Debug)]
@@ -33,6 +46,8 @@ pub enum Error {
     UserDictionaryParseError,
     #[error("User defined dictionary file size exceeded")]
     UserDictionarySizeExceed,
+    #[error("Failed to read user defined dictionary file")]
+    UserDictionaryReadError(#[from] std::io::Error),
     #[error("Requests per minute exceeded")]
     RateLimited,
     #[error(transparent)]
@@ -74,10 +89,116 @@ pub enum Level {
     Lv3,
 }
 
+/// Articut's part-of-speech tag, coarsened from the raw tag string the API returns.
+/// Unrecognized tags fall back to [`Pos::Other`] so new tags don't break deserialization.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pos {
+    Noun,
+    Verb,
+    Time,
+    Location,
+    Pronoun,
+    Number,
+    Modifier,
+    Conjunction,
+    Preposition,
+    Particle,
+    Punctuation,
+    Other(String),
+}
+
+impl Pos {
+    pub fn is_noun(&self) -> bool {
+        matches!(self, Pos::Noun)
+    }
+
+    pub fn is_verb(&self) -> bool {
+        matches!(self, Pos::Verb)
+    }
+
+    pub fn is_time(&self) -> bool {
+        matches!(self, Pos::Time)
+    }
+
+    pub fn is_location(&self) -> bool {
+        matches!(self, Pos::Location)
+    }
+
+    pub fn is_pronoun(&self) -> bool {
+        matches!(self, Pos::Pronoun)
+    }
+
+    pub fn is_punctuation(&self) -> bool {
+        matches!(self, Pos::Punctuation)
+    }
+}
+
+impl From<&str> for Pos {
+    fn from(tag: &str) -> Self {
+        // Articut tags are frequently compound (e.g. `ENTITY_nouns_location`), so arms are
+        // ordered most-specific-first: a tag naming a concrete category (time, location,
+        // pronoun, number, punctuation, particle, conjunction, preposition) wins over the
+        // broad `noun`/`verb` catch-alls, which are checked last.
+        match tag {
+            s if s.contains("time") || s.contains("Time") || s.contains("DATE") => Pos::Time,
+            s if s.contains("location") || s.contains("LOCATION") => Pos::Location,
+            s if s.contains("pronoun") => Pos::Pronoun,
+            s if s.contains("number") || s.contains("COUNT") => Pos::Number,
+            s if s.contains("punctuation") || s.contains("PUNCTUATION") => Pos::Punctuation,
+            s if s.contains("particle") || s.contains("FUNCTION") => Pos::Particle,
+            s if s.contains("conjunction") => Pos::Conjunction,
+            s if s.contains("preposition") => Pos::Preposition,
+            s if s.contains("modifier") || s.contains("MODIFIER") => Pos::Modifier,
+            s if s.contains("noun") || s.contains("Noun") => Pos::Noun,
+            s if s.contains("verb") || s.contains("Verb") => Pos::Verb,
+            other => Pos::Other(other.to_string()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Pos {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let tag = String::deserialize(deserializer)?;
+        Ok(Pos::from(tag.as_str()))
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct PosTag {
-    pos: String, // TODO: Make this a enum
-    text: String,
+    pub pos: Pos,
+    pub text: String,
+    /// Pinyin/Bopomofo reading for this token, present when `pinyin` was requested.
+    #[serde(default)]
+    pub pinyin: Option<String>,
+}
+
+impl PosTag {
+    pub fn is_noun(&self) -> bool {
+        self.pos.is_noun()
+    }
+
+    pub fn is_verb(&self) -> bool {
+        self.pos.is_verb()
+    }
+
+    pub fn is_time(&self) -> bool {
+        self.pos.is_time()
+    }
+
+    pub fn is_location(&self) -> bool {
+        self.pos.is_location()
+    }
+
+    pub fn is_pronoun(&self) -> bool {
+        self.pos.is_pronoun()
+    }
+
+    pub fn is_punctuation(&self) -> bool {
+        self.pos.is_punctuation()
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -119,6 +240,35 @@ pub struct RequestOptions {
     pub pinyin: Pinyin,
 }
 
+impl RequestOptions {
+    /// Maximum serialized size, in bytes, Articut accepts for a user-defined dictionary.
+    pub const MAX_USER_DICT_SIZE: usize = 100_000;
+
+    /// Reads a user-defined dictionary (a JSON object mapping terms to their POS tag) from
+    /// `path` and installs it on these options. Rejects the dictionary locally with
+    /// [`Error::UserDictionarySizeExceed`] if it would exceed [`Self::MAX_USER_DICT_SIZE`]
+    /// once serialized, instead of spending a request to find out. The raw text is also
+    /// rejected with that error, before parsing, if it is itself longer than the limit.
+    pub fn user_dict_from_file(self, path: impl AsRef<Path>) -> Result<Self, Error> {
+        let raw = std::fs::read_to_string(path)?;
+        self.user_dict_from_json(&raw)
+    }
+
+    /// Same as [`Self::user_dict_from_file`] but takes the dictionary's raw JSON text directly.
+    pub fn user_dict_from_json(mut self, raw: &str) -> Result<Self, Error> {
+        if raw.len() > Self::MAX_USER_DICT_SIZE {
+            return Err(Error::UserDictionarySizeExceed);
+        }
+        let dict: HashMap<String, String> =
+            serde_json::from_str(raw).map_err(|_| Error::UserDictionaryParseError)?;
+        if serde_json::to_string(&dict).map_or(true, |s| s.len() > Self::MAX_USER_DICT_SIZE) {
+            return Err(Error::UserDictionarySizeExceed);
+        }
+        self.user_dict = dict;
+        Ok(self)
+    }
+}
+
 lazy_static! {
     /// This is an example for using doc comment attributes
     static ref DEFAULT_OPTION: RequestOptions = {
@@ -141,16 +291,57 @@ impl<'a, 'b> Articut<'a, 'b> {
         Articut {
             username,
             api_key,
-            client: None,
+            client: reqwest::Client::new(),
+            rate_limit: None,
+            request_log: tokio::sync::Mutex::new(VecDeque::new()),
+            quota_precheck: false,
+            last_quota: tokio::sync::Mutex::new(None),
         }
     }
 
-    pub async fn parse(self, text: &str) -> Result<Response, Error> {
+    /// Returns the word-count balance observed on the most recent successful response,
+    /// or `None` before any request has succeeded (or after [`Self::reset_quota`]).
+    /// Responses that map to an [`Error`] leave the cached balance unchanged.
+    pub async fn remaining_quota(&self) -> Option<i32> {
+        *self.last_quota.lock().await
+    }
+
+    /// Forgets the cached word-count balance, so the next `parse`/`parse_full_options`
+    /// call skips the local quota precheck and goes straight to the server. Useful after
+    /// topping up quota out-of-band, since the cached balance would otherwise never be
+    /// refreshed for texts longer than the previously observed balance.
+    pub async fn reset_quota(&self) {
+        *self.last_quota.lock().await = None;
+    }
+
+    /// Enables a client-side precheck that rejects `parse`/`parse_full_options` calls
+    /// with [`Error::NotEnoughQuota`] up front when the input is longer than the balance
+    /// observed on the last response, instead of spending a request to find out. Off by
+    /// default, since the cached balance can go stale if quota is topped up out-of-band —
+    /// call [`Self::reset_quota`] to force the next call through to the server.
+    pub fn with_quota_precheck(mut self) -> Self {
+        self.quota_precheck = true;
+        self
+    }
+
+    /// Enables a sliding-window request limiter of `max_per_minute` sends, and retries
+    /// responses that come back as [`Error::RateLimited`] up to `max_retries` times with
+    /// exponential backoff (500ms, 1s, 2s, ...). Off by default. `max_per_minute` is
+    /// clamped to at least 1, since a zero-sized window could never admit a request.
+    pub fn with_rate_limit(mut self, max_per_minute: usize, max_retries: u32) -> Self {
+        self.rate_limit = Some(RateLimit {
+            max_per_minute: max_per_minute.max(1),
+            max_retries,
+        });
+        self
+    }
+
+    pub async fn parse(&self, text: &str) -> Result<Response, Error> {
         self.parse_full_options(text, DEFAULT_OPTION.clone()).await
     }
 
     pub async fn parse_full_options(
-        mut self,
+        &self,
         text: &str,
         option: RequestOptions,
     ) -> Result<Response, Error> {
@@ -163,21 +354,172 @@ impl<'a, 'b> Articut<'a, 'b> {
             api_key: &'b str,
         }
 
-        let client = self.client.get_or_insert_with(|| reqwest::Client::new());
+        if self.quota_precheck {
+            if let Some(balance) = *self.last_quota.lock().await {
+                if text.chars().count() as i32 > balance {
+                    return Err(Error::NotEnoughQuota);
+                }
+            }
+        }
+
         let payload = &Payload {
             opt: option,
             input_str: text.to_string(),
             username: self.username,
             api_key: self.api_key,
         };
-        client
-            .post("https://api.droidtown.co/Articut/API/")
-            .json(&payload)
-            .send()
-            .await?
-            .json::<Response>()
-            .await
-            .map_err(Into::into)
-            .and_then(|res| Error::from_str(&res.msg).map_or(Ok(res), |e| Err(e.into())))
+
+        let max_retries = self.rate_limit.as_ref().map_or(0, |r| r.max_retries);
+        let mut attempt = 0;
+        loop {
+            self.throttle().await;
+            let result = self
+                .client
+                .post("https://api.droidtown.co/Articut/API/")
+                .json(&payload)
+                .send()
+                .await?
+                .json::<Response>()
+                .await
+                .map_err(Into::into)
+                .and_then(|res| Error::from_str(&res.msg).map_or(Ok(res), |e| Err(e.into())));
+
+            match result {
+                Err(Error::RateLimited) if attempt < max_retries => {
+                    tokio::time::sleep(Duration::from_millis(500 * 2u64.pow(attempt))).await;
+                    attempt += 1;
+                }
+                Ok(ref res) => {
+                    *self.last_quota.lock().await = Some(res.word_count_balance);
+                    return result;
+                }
+                other => return other,
+            }
+        }
+    }
+
+    /// Parses many documents concurrently, with at most `concurrency` requests in flight
+    /// at once (respecting any configured rate limit), and returns one `Result` per input
+    /// text in the same order as `texts`.
+    pub async fn parse_batch(
+        &self,
+        texts: &[&str],
+        option: RequestOptions,
+        concurrency: usize,
+    ) -> Vec<Result<Response, Error>> {
+        let mut results = stream::iter(texts.iter().enumerate())
+            .map(|(i, text)| {
+                let option = option.clone();
+                async move { (i, self.parse_full_options(text, option).await) }
+            })
+            .buffer_unordered(concurrency.max(1))
+            .collect::<Vec<_>>()
+            .await;
+
+        results.sort_by_key(|(i, _)| *i);
+        results.into_iter().map(|(_, res)| res).collect()
+    }
+
+    /// Blocks until a send is allowed under the configured sliding-window limit, then
+    /// records this send. A no-op when no rate limit has been configured.
+    async fn throttle(&self) {
+        let limit = match &self.rate_limit {
+            Some(limit) => limit,
+            None => return,
+        };
+        loop {
+            let wait = {
+                let mut log = self.request_log.lock().await;
+                let now = Instant::now();
+                while matches!(log.front(), Some(t) if now.duration_since(*t) >= Duration::from_secs(60))
+                {
+                    log.pop_front();
+                }
+                if log.len() < limit.max_per_minute {
+                    log.push_back(now);
+                    return;
+                }
+                let oldest = *log.front().unwrap();
+                Duration::from_secs(60).saturating_sub(now.duration_since(oldest))
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn with_rate_limit_clamps_zero_to_one() {
+        let articut = Articut::new("user", "key").with_rate_limit(0, 0);
+        assert_eq!(articut.rate_limit.unwrap().max_per_minute, 1);
+    }
+
+    #[tokio::test]
+    async fn throttle_with_zero_limit_does_not_panic() {
+        let articut = Articut::new("user", "key").with_rate_limit(0, 0);
+        articut.throttle().await;
+    }
+
+    #[test]
+    fn pos_from_prefers_specific_category_for_compound_tags() {
+        assert_eq!(Pos::from("ENTITY_nouns_location"), Pos::Location);
+        assert_eq!(Pos::from("ACTION_verb_time"), Pos::Time);
+        assert_eq!(Pos::from("FUNCTION_particle_conjunction"), Pos::Particle);
+    }
+
+    #[test]
+    fn pos_from_falls_back_to_other_for_unknown_tags() {
+        assert_eq!(
+            Pos::from("TOTALLY_UNKNOWN_TAG"),
+            Pos::Other("TOTALLY_UNKNOWN_TAG".to_string())
+        );
+    }
+
+    #[test]
+    fn user_dict_from_json_rejects_oversized_payload() {
+        let huge = format!(
+            "{{\"{}\":\"N\"}}",
+            "x".repeat(RequestOptions::MAX_USER_DICT_SIZE)
+        );
+        let result = DEFAULT_OPTION.clone().user_dict_from_json(&huge);
+        assert!(matches!(result, Err(Error::UserDictionarySizeExceed)));
+    }
+
+    #[test]
+    fn user_dict_from_json_rejects_malformed_json() {
+        let result = DEFAULT_OPTION.clone().user_dict_from_json("not json");
+        assert!(matches!(result, Err(Error::UserDictionaryParseError)));
+    }
+
+    #[test]
+    fn user_dict_from_file_reports_io_error_for_missing_file() {
+        let result = DEFAULT_OPTION
+            .clone()
+            .user_dict_from_file("/no/such/path.json");
+        assert!(matches!(result, Err(Error::UserDictionaryReadError(_))));
+    }
+
+    #[tokio::test]
+    async fn quota_precheck_rejects_locally_when_enabled() {
+        let articut = Articut::new("user", "key").with_quota_precheck();
+        *articut.last_quota.lock().await = Some(1);
+        // The cached balance (1) is smaller than the input length, so this must be
+        // rejected before ever touching `self.client`.
+        let result = articut
+            .parse_full_options("too long for quota", DEFAULT_OPTION.clone())
+            .await;
+        assert!(matches!(result, Err(Error::NotEnoughQuota)));
+    }
+
+    #[tokio::test]
+    async fn reset_quota_clears_the_cached_balance() {
+        let articut = Articut::new("user", "key");
+        *articut.last_quota.lock().await = Some(1);
+        assert_eq!(articut.remaining_quota().await, Some(1));
+        articut.reset_quota().await;
+        assert_eq!(articut.remaining_quota().await, None);
     }
 }